@@ -1,21 +1,358 @@
 use crate::web::page::TemplateData;
+use encoding_rs::{Encoding, UTF_8};
 use lol_html::errors::RewritingError;
+use std::fmt;
+use std::io::{self, Write};
 use tera::Context;
 
+/// The size of the chunks the source HTML is split into before being fed to
+/// the rewriter. lol-html is built to operate on a stream of small chunks
+/// rather than one large buffer, so this keeps peak memory bounded even for
+/// multi-MB rustdoc pages.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// How many leading bytes of a page we scan for a `<meta charset>` /
+/// `Content-Type` declaration. Rustdoc always emits its charset near the top
+/// of `<head>`, so this bounds the scan without reading a multi-MB page.
+const CHARSET_SNIFF_LEN: usize = 1024;
+
+/// Maximum length, in bytes, of the generated meta-description / OpenGraph
+/// snippet, not counting the `…` appended when it is truncated.
+const DESCRIPTION_SNIPPET_LEN: usize = 175;
+
+/// Errors produced while rewriting a rustdoc page.
+#[derive(Debug)]
+pub(crate) enum HtmlRewriteError {
+    /// The page declared a charset that `encoding_rs` doesn't recognize, so
+    /// it couldn't be transcoded to UTF-8.
+    UndecodableInput(String),
+    /// lol-html failed while rewriting already-UTF-8 content.
+    Rewriting(RewritingError),
+    /// Writing the rewritten output to the caller's `writer` failed, e.g.
+    /// because the client disconnected mid-response.
+    WriteFailed(io::Error),
+}
+
+impl fmt::Display for HtmlRewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndecodableInput(label) => {
+                write!(f, "rustdoc page declared unrecognized charset {label:?}")
+            }
+            Self::Rewriting(err) => write!(f, "{err}"),
+            Self::WriteFailed(err) => write!(f, "failed to write rewritten HTML: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for HtmlRewriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::UndecodableInput(_) => None,
+            Self::Rewriting(err) => Some(err),
+            Self::WriteFailed(err) => Some(err),
+        }
+    }
+}
+
+impl From<RewritingError> for HtmlRewriteError {
+    fn from(err: RewritingError) -> Self {
+        Self::Rewriting(err)
+    }
+}
+
+/// Sniff the charset a page declares via a leading BOM or a `<meta charset>`
+/// / `<meta http-equiv="Content-Type">` tag, defaulting to UTF-8 when none is
+/// declared.
+fn sniff_declared_charset(html: &[u8]) -> Result<&'static Encoding, HtmlRewriteError> {
+    if let Some((encoding, _bom_len)) = Encoding::for_bom(html) {
+        return Ok(encoding);
+    }
+
+    let prefix = String::from_utf8_lossy(&html[..html.len().min(CHARSET_SNIFF_LEN)]).to_lowercase();
+    // Only look for `charset=` inside a `<meta ...>` tag, so text elsewhere
+    // near the top of the page (e.g. in `<title>`) isn't mistaken for one.
+    let Some(label_start) = prefix.match_indices("<meta").find_map(|(meta_start, _)| {
+        let tag = &prefix[meta_start..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        tag.find("charset=").map(|offset| meta_start + offset)
+    }) else {
+        return Ok(UTF_8);
+    };
+    let rest = prefix[label_start + "charset=".len()..].trim_start_matches(['"', '\'']);
+    let label_end = rest
+        .find(|c: char| c == '"' || c == '\'' || c == '>' || c == ';' || c.is_whitespace())
+        .unwrap_or(rest.len());
+    let label = &rest[..label_end];
+
+    Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| HtmlRewriteError::UndecodableInput(label.to_string()))
+}
+
+/// Transcode `html` from `encoding` to UTF-8, using `encoding_rs`'s
+/// incremental decoder and replacing malformed sequences with U+FFFD rather
+/// than erroring.
+fn transcode_to_utf8(html: &[u8], encoding: &'static Encoding) -> Vec<u8> {
+    let mut decoder = encoding.new_decoder();
+    let mut output = String::with_capacity(
+        decoder
+            .max_utf8_buffer_length(html.len())
+            .unwrap_or(html.len()),
+    );
+    let (_result, _read, _had_errors) = decoder.decode_to_string(html, &mut output, true);
+    output.into_bytes()
+}
+
+/// Extract a plain-text snippet from the first top-level `.docblock`
+/// paragraph in `html`, for use as a meta description / OpenGraph
+/// description.
+///
+/// This runs as a cheap first pass over the bytes feeding a throwaway
+/// rewriter that discards its output: since `<head>` is emitted before the
+/// rewriter in [`rewrite_lol_to_writer`] ever reaches the body, there's no
+/// way to pick up the snippet from the single streaming pass below before
+/// `head.html` needs it.
+///
+/// The scan stops feeding `html` into the rewriter as soon as the first
+/// `.docblock > p` has closed, rather than tokenizing the rest of the page.
+/// Without that, this pass would tokenize an entire multi-MB rustdoc page a
+/// second time on top of the real rewrite in [`rewrite_lol_to_writer`],
+/// doubling tokenization cost on exactly the pages that function is trying
+/// to avoid fully buffering.
+fn extract_description_snippet(html: &[u8], max_allowed_memory_usage: usize) -> Option<String> {
+    use lol_html::html_content::{Element, TextChunk};
+    use lol_html::{ElementContentHandlers, HtmlRewriter, MemorySettings, Settings};
+    use std::cell::{Cell, RefCell};
+
+    // Only the first matched paragraph's text is kept; `lol-html` processes
+    // content in document order, so the first `.docblock > p` it sees is the
+    // page's own summary paragraph.
+    let paragraph_count = Cell::new(0u32);
+    let done = Cell::new(false);
+    let text = RefCell::new(String::new());
+
+    let paragraph_selector = ".docblock > p".parse().unwrap();
+    let element_handler = |el: &mut Element| {
+        paragraph_count.set(paragraph_count.get() + 1);
+        if paragraph_count.get() == 1 {
+            // Once the summary paragraph's own end tag is hit, nothing past
+            // it is ever used, so stop the outer feed loop there instead of
+            // tokenizing the rest of the document.
+            el.on_end_tag(|_| {
+                done.set(true);
+                Ok(())
+            })?;
+        }
+        Ok(())
+    };
+    let text_handler = |chunk: &mut TextChunk| {
+        if paragraph_count.get() == 1 {
+            text.borrow_mut().push_str(chunk.as_str());
+        }
+        Ok(())
+    };
+
+    let element_content_handlers = vec![(
+        &paragraph_selector,
+        ElementContentHandlers::default()
+            .element(element_handler)
+            .text(text_handler),
+    )];
+    let settings = Settings {
+        element_content_handlers,
+        memory_settings: MemorySettings {
+            max_allowed_memory_usage,
+            ..MemorySettings::default()
+        },
+        ..Settings::default()
+    };
+
+    let mut writer = HtmlRewriter::try_new(settings, |_: &[u8]| {}).ok()?;
+    for chunk in html.chunks(CHUNK_SIZE) {
+        writer.write(chunk).ok()?;
+        if done.get() {
+            break;
+        }
+    }
+    // Only finalize the rewriter when the whole input was actually fed to
+    // it; when `done` short-circuited the loop above, the rewriter may be
+    // sitting mid-tag past the point we stopped, and `end()` would reject
+    // that, even though the snippet text was already captured.
+    if !done.get() {
+        writer.end().ok()?;
+    }
+
+    let snippet = text.into_inner();
+    let trimmed = snippet.trim();
+    (!trimmed.is_empty()).then(|| truncate_snippet(&decode_entities(trimmed)))
+}
+
+/// Decode the handful of HTML character references that actually show up in
+/// docblock text (`&amp;`, `&lt;`, `&gt;`, `&quot;`, `&apos;`, and numeric
+/// references) so a meta description doesn't carry markup escaping that
+/// would get double-escaped when rendered through an autoescaping template.
+/// Unrecognized references (e.g. `&nbsp;`) are left as-is.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let Some(semi) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..semi];
+        match decode_entity(entity) {
+            Some(decoded) => out.push(decoded),
+            None => out.push_str(&rest[..=semi]),
+        }
+        rest = &rest[semi + 1..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Decode a single HTML character reference's name (the text between `&`
+/// and `;`), returning `None` for references we don't recognize.
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let codepoint = if let Some(hex) = entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                u32::from_str_radix(hex, 16).ok()?
+            } else {
+                entity.strip_prefix('#')?.parse().ok()?
+            };
+            char::from_u32(codepoint)
+        }
+    }
+}
+
+/// Truncate `text` to at most [`DESCRIPTION_SNIPPET_LEN`] bytes followed by a
+/// `…`, cutting on a char boundary and backing out of a trailing `&entity;`
+/// reference so one is never split in half.
+fn truncate_snippet(text: &str) -> String {
+    if text.len() <= DESCRIPTION_SNIPPET_LEN {
+        return text.to_string();
+    }
+
+    let mut end = DESCRIPTION_SNIPPET_LEN;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    if let Some(amp) = text[..end].rfind('&') {
+        // Only a cut-off reference (`&` followed by nothing but name or
+        // number characters) is backed out of, not a literal ampersand.
+        if text[amp + 1..end]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '#')
+        {
+            end = amp;
+        }
+    }
+
+    format!("{}…", text[..end].trim_end())
+}
+
+/// The rustdoc theme stylesheet names recognized by [`theme_of_stylesheet_href`].
+const THEME_STYLESHEET_NAMES: [&str; 3] = ["light", "dark", "ayu"];
+
+/// If `href`'s final path segment is one of rustdoc's own theme stylesheet
+/// files (`light.css`, `dark.css`, `ayu.css`, optionally followed by a
+/// `-`-separated suffix such as an asset hash, like
+/// `light-db279b6232be9c13.css`), return which theme it is.
+///
+/// The theme name must be the whole filename stem or the part of it before
+/// the first `-`; the text after that `-` is not checked. This rather than
+/// matching loosely on substring containment, which would also catch
+/// unrelated stylesheets that merely contain a theme name, like
+/// `highlight.css` or `syntax-light.css`.
+fn theme_of_stylesheet_href(href: &str) -> Option<&'static str> {
+    let filename = href.rsplit('/').next().unwrap_or(href);
+    let stem = filename.strip_suffix(".css")?;
+    THEME_STYLESHEET_NAMES.into_iter().find(|&name| {
+        stem == name
+            || stem
+                .strip_prefix(name)
+                .is_some_and(|rest| rest.starts_with('-'))
+    })
+}
+
 /// Rewrite a rustdoc page to have the docs.rs header
 ///
 /// Given a rustdoc HTML page and a context to serialize it with,
 /// render the `rustdoc/` templates with the `html`.
 /// The output is an HTML page which has not yet been UTF-8 validated.
 /// In practice, the output should always be valid UTF-8.
+///
+/// This buffers the entire rewritten page in memory before returning it.
+/// Prefer [`rewrite_lol_to_writer`] when the caller can stream the output
+/// directly, e.g. into an HTTP response body.
 pub(crate) fn rewrite_lol(
     html: &[u8],
     max_allowed_memory_usage: usize,
     ctx: Context,
     templates: &TemplateData,
-) -> Result<Vec<u8>, RewritingError> {
+) -> Result<Vec<u8>, HtmlRewriteError> {
+    let mut buffer = Vec::new();
+    rewrite_lol_to_writer(html, max_allowed_memory_usage, ctx, templates, &mut buffer)?;
+    Ok(buffer)
+}
+
+/// Rewrite a rustdoc page to have the docs.rs header, streaming the output
+/// directly into `writer` instead of buffering it.
+///
+/// This feeds the source `html` into the rewriter in bounded chunks, so
+/// bytes start flowing to `writer` before the whole document has been
+/// processed, and peak memory usage stays proportional to `CHUNK_SIZE`
+/// rather than the size of the page. The exception is a page that isn't
+/// UTF-8: it is first transcoded into a full-size UTF-8 copy.
+///
+/// Note: the lower-peak-memory, earlier-first-byte benefit only shows up
+/// once a caller streams `writer` straight into an HTTP response body as
+/// it's written, instead of writing to an in-memory buffer first. In this
+/// crate it is called by [`rewrite_lol`], which collects the output into a
+/// `Vec`, and by [`render_markdown_page`], which passes its own caller's
+/// `writer` through. Reach for [`rewrite_lol`] for anything that isn't
+/// already writing to a streaming sink.
+///
+/// [`render_markdown_page`]: crate::utils::markdown::render_markdown_page
+pub(crate) fn rewrite_lol_to_writer(
+    html: &[u8],
+    max_allowed_memory_usage: usize,
+    mut ctx: Context,
+    templates: &TemplateData,
+    writer: &mut dyn Write,
+) -> Result<(), HtmlRewriteError> {
     use lol_html::html_content::{ContentType, Element};
     use lol_html::{ElementContentHandlers, HtmlRewriter, MemorySettings, Settings};
+    use std::cell::RefCell;
+
+    // Old or unusually-configured builds can emit pages in a non-UTF-8
+    // charset; transcode those to UTF-8 before handing them to the
+    // rewriter, which assumes valid UTF-8 input and output.
+    let charset = sniff_declared_charset(html)?;
+    let transcoded;
+    let html: &[u8] = if charset == UTF_8 {
+        html
+    } else {
+        transcoded = transcode_to_utf8(html, charset);
+        &transcoded
+    };
+
+    if let Some(snippet) = extract_description_snippet(html, max_allowed_memory_usage) {
+        ctx.insert("meta_description", &snippet);
+    }
 
     let templates = templates.templates.load();
     let tera_head = templates.render("rustdoc/head.html", &ctx).unwrap();
@@ -72,10 +409,88 @@ pub(crate) fn rewrite_lol(
         Ok(())
     };
 
-    let (head_selector, body_selector, first_stylesheet_selector) = (
+    // Since the page has (possibly) just been transcoded to UTF-8, make sure
+    // any declared charset reflects that rather than the original encoding.
+    let meta_charset_handler = |meta: &mut Element| {
+        if meta.has_attribute("charset") {
+            meta.set_attribute("charset", "utf-8")?;
+        } else if let Some(content) = meta.get_attribute("content") {
+            if content.to_ascii_lowercase().contains("charset=") {
+                meta.set_attribute("content", "text/html; charset=utf-8")?;
+            }
+        }
+
+        Ok(())
+    };
+
+    // The theme the caller resolved from a cookie / `prefers-color-scheme`
+    // hint (see `Context` construction in the request handler), if any. When
+    // unset, rustdoc's own client-side theme detection takes over as before.
+    let requested_theme = ctx
+        .get("theme")
+        .and_then(|value| value.as_str())
+        .map(str::to_owned);
+
+    // Rustdoc ships the default theme's stylesheet enabled and the rest
+    // `disabled`, with its JS swapping which one is active on load. Flip
+    // `disabled` on every theme link here instead, so exactly the requested
+    // theme is active on first paint and no other theme stays enabled
+    // alongside it.
+    //
+    // This matches against every `link[rel='stylesheet']`, not just the
+    // three rustdoc theme files, because `theme_of_stylesheet_href` below
+    // does the precise filtering; a selector can only narrow on substring
+    // containment, which would also catch unrelated stylesheets shipped
+    // alongside rustdoc's (e.g. `highlight.css`, `syntax-light.css`).
+    let theme_stylesheet_handler = |link: &mut Element| {
+        let Some(requested) = requested_theme.as_deref() else {
+            return Ok(());
+        };
+        let Some(href) = link.get_attribute("href") else {
+            return Ok(());
+        };
+        let Some(theme) = theme_of_stylesheet_href(&href) else {
+            return Ok(());
+        };
+        if theme == requested {
+            link.remove_attribute("disabled");
+        } else if !link.has_attribute("disabled") {
+            link.set_attribute("disabled", "")?;
+        }
+
+        Ok(())
+    };
+
+    // Rustdoc's own JS reads the active theme off `#rustdoc-vars`'s data
+    // attributes; keep it in sync with the stylesheet we just enabled.
+    let rustdoc_vars_handler = |vars: &mut Element| {
+        if let Some(theme) = requested_theme.as_deref() {
+            vars.set_attribute("data-theme", theme)?;
+        }
+
+        Ok(())
+    };
+
+    let (
+        head_selector,
+        body_selector,
+        first_stylesheet_selector,
+        meta_charset_selector,
+        meta_http_equiv_selector,
+        stylesheet_selector,
+        rustdoc_vars_selector,
+    ) = (
         "head".parse().unwrap(),
         "body".parse().unwrap(),
         "link[type='text/css'][href*='rustdoc']".parse().unwrap(),
+        "meta[charset]".parse().unwrap(),
+        "meta[http-equiv='Content-Type']".parse().unwrap(),
+        // Match every stylesheet link regardless of whether it currently
+        // carries `disabled`, so the handler can toggle any of them, not
+        // just the ones that start out disabled; `theme_stylesheet_handler`
+        // itself decides which of these are actually rustdoc theme files.
+        "link[rel='stylesheet']".parse().unwrap(),
+        "#rustdoc-vars".parse().unwrap(),
     );
     let element_content_handlers = vec![
         (
@@ -90,6 +505,22 @@ pub(crate) fn rewrite_lol(
             &first_stylesheet_selector,
             ElementContentHandlers::default().element(first_stylesheet_handler),
         ),
+        (
+            &meta_charset_selector,
+            ElementContentHandlers::default().element(meta_charset_handler),
+        ),
+        (
+            &meta_http_equiv_selector,
+            ElementContentHandlers::default().element(meta_charset_handler),
+        ),
+        (
+            &stylesheet_selector,
+            ElementContentHandlers::default().element(theme_stylesheet_handler),
+        ),
+        (
+            &rustdoc_vars_selector,
+            ElementContentHandlers::default().element(rustdoc_vars_handler),
+        ),
     ];
     let settings = Settings {
         element_content_handlers,
@@ -101,15 +532,248 @@ pub(crate) fn rewrite_lol(
     };
 
     // The input and output are always strings, we just use `&[u8]` so we only have to validate once.
-    let mut buffer = Vec::new();
-    // TODO: Make the rewriter persistent?
-    let mut writer = HtmlRewriter::try_new(settings, |bytes: &[u8]| {
-        buffer.extend_from_slice(bytes);
+    // lol-html's output sink can't return a `Result`, so a failure to write to
+    // `writer` (e.g. a client disconnecting mid-response) is stashed here
+    // instead, and surfaced as an error once the rewrite finishes.
+    let write_error: RefCell<Option<io::Error>> = RefCell::new(None);
+    let mut rewriter = HtmlRewriter::try_new(settings, |bytes: &[u8]| {
+        if write_error.borrow().is_some() {
+            return;
+        }
+        if let Err(err) = writer.write_all(bytes) {
+            *write_error.borrow_mut() = Some(err);
+        }
     })
     .expect("utf8 is a valid encoding");
 
-    writer.write(html)?;
-    writer.end()?;
+    // Feed the source HTML in bounded chunks rather than one `write(html)`
+    // call, so lol-html can start flushing rewritten output to `writer`
+    // before the whole document has been read.
+    for chunk in html.chunks(CHUNK_SIZE) {
+        rewriter.write(chunk)?;
+    }
+    rewriter.end()?;
 
-    Ok(buffer)
+    if let Some(err) = write_error.into_inner() {
+        return Err(HtmlRewriteError::WriteFailed(err));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_declared_charset_defaults_to_utf8_when_undeclared() {
+        let html = b"<html><head></head><body>hi</body></html>";
+        assert_eq!(sniff_declared_charset(html).unwrap(), UTF_8);
+    }
+
+    #[test]
+    fn sniff_declared_charset_detects_utf16_bom() {
+        let html = b"\xff\xfe<\x00h\x00>\x00";
+        assert_eq!(sniff_declared_charset(html).unwrap(), encoding_rs::UTF_16LE);
+    }
+
+    #[test]
+    fn sniff_declared_charset_reads_meta_charset_attribute() {
+        let html = b"<head><meta charset=\"windows-1252\"></head>";
+        assert_eq!(
+            sniff_declared_charset(html).unwrap(),
+            encoding_rs::WINDOWS_1252
+        );
+    }
+
+    #[test]
+    fn sniff_declared_charset_reads_http_equiv_content_type() {
+        let html =
+            b"<head><meta http-equiv='Content-Type' content='text/html; charset=shift_jis'></head>";
+        assert_eq!(
+            sniff_declared_charset(html).unwrap(),
+            encoding_rs::SHIFT_JIS
+        );
+    }
+
+    #[test]
+    fn sniff_declared_charset_rejects_unrecognized_label() {
+        let html = b"<head><meta charset=\"not-a-real-charset\"></head>";
+        match sniff_declared_charset(html) {
+            Err(HtmlRewriteError::UndecodableInput(label)) => {
+                assert_eq!(label, "not-a-real-charset");
+            }
+            other => panic!("expected UndecodableInput, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn transcode_to_utf8_converts_windows_1252_bytes() {
+        // 0x93/0x94 are curly quotes in windows-1252, undefined in UTF-8.
+        let input = b"\x93hi\x94";
+        let output = transcode_to_utf8(input, encoding_rs::WINDOWS_1252);
+        assert_eq!(String::from_utf8(output).unwrap(), "\u{201c}hi\u{201d}");
+    }
+
+    #[test]
+    fn transcode_to_utf8_replaces_malformed_sequences() {
+        // 0xFF is never valid in Shift_JIS, so it must become U+FFFD rather
+        // than erroring out.
+        let input = b"a\xffb";
+        let output = transcode_to_utf8(input, encoding_rs::SHIFT_JIS);
+        assert_eq!(String::from_utf8(output).unwrap(), "a\u{fffd}b");
+    }
+
+    #[test]
+    fn truncate_snippet_leaves_short_text_untouched() {
+        assert_eq!(truncate_snippet("short description"), "short description");
+    }
+
+    #[test]
+    fn truncate_snippet_cuts_long_text_with_ellipsis() {
+        let text = "a".repeat(DESCRIPTION_SNIPPET_LEN + 50);
+        let truncated = truncate_snippet(&text);
+        assert!(truncated.ends_with('…'));
+        assert!(truncated.len() <= DESCRIPTION_SNIPPET_LEN + '…'.len_utf8());
+    }
+
+    #[test]
+    fn truncate_snippet_never_splits_a_multibyte_char() {
+        // Each "é" is two UTF-8 bytes, so a byte-oriented cut at the exact
+        // limit would otherwise land mid-character.
+        let text = "é".repeat(DESCRIPTION_SNIPPET_LEN);
+        let truncated = truncate_snippet(&text);
+        assert!(truncated.is_char_boundary(truncated.len() - '…'.len_utf8()));
+    }
+
+    #[test]
+    fn truncate_snippet_backs_out_of_a_split_entity() {
+        // Pad so the cut point lands inside `&amp;`.
+        let padding = "a".repeat(DESCRIPTION_SNIPPET_LEN - 3);
+        let text = format!("{padding}&amp;more text that keeps going");
+        let truncated = truncate_snippet(&text);
+        assert_eq!(truncated, format!("{padding}…"));
+    }
+
+    #[test]
+    fn decode_entities_handles_named_and_numeric_references() {
+        assert_eq!(decode_entities("Vec&lt;T&gt;"), "Vec<T>");
+        assert_eq!(decode_entities("Tom &amp; Jerry"), "Tom & Jerry");
+        assert_eq!(decode_entities("quote: &quot;hi&quot;"), "quote: \"hi\"");
+        assert_eq!(decode_entities("&#65;&#x42;"), "AB");
+    }
+
+    #[test]
+    fn decode_entities_leaves_unrecognized_references_alone() {
+        assert_eq!(decode_entities("a&nbsp;b"), "a&nbsp;b");
+    }
+
+    #[test]
+    fn theme_of_stylesheet_href_matches_exact_and_hashed_filenames() {
+        assert_eq!(theme_of_stylesheet_href("light.css"), Some("light"));
+        assert_eq!(
+            theme_of_stylesheet_href("static/dark-db279b6232be9c13.css"),
+            Some("dark")
+        );
+        assert_eq!(theme_of_stylesheet_href("ayu.css"), Some("ayu"));
+    }
+
+    #[test]
+    fn theme_of_stylesheet_href_rejects_substring_matches() {
+        assert_eq!(theme_of_stylesheet_href("highlight.css"), None);
+        assert_eq!(theme_of_stylesheet_href("syntax-light.css"), None);
+        assert_eq!(theme_of_stylesheet_href("darker-theme.css"), None);
+    }
+
+    /// Minimal standalone rewrite exercising just the theme-stylesheet and
+    /// `#rustdoc-vars` handling from [`rewrite_lol_to_writer`], without
+    /// needing a full `TemplateData` to render the surrounding page chrome.
+    fn rewrite_theme_links_for_test(html: &str, requested_theme: Option<&str>) -> String {
+        use lol_html::html_content::Element;
+        use lol_html::{ElementContentHandlers, HtmlRewriter, Settings};
+
+        let requested_theme = requested_theme.map(str::to_owned);
+
+        let theme_stylesheet_handler = |link: &mut Element| {
+            let Some(requested) = requested_theme.as_deref() else {
+                return Ok(());
+            };
+            let Some(href) = link.get_attribute("href") else {
+                return Ok(());
+            };
+            let Some(theme) = theme_of_stylesheet_href(&href) else {
+                return Ok(());
+            };
+            if theme == requested {
+                link.remove_attribute("disabled");
+            } else if !link.has_attribute("disabled") {
+                link.set_attribute("disabled", "")?;
+            }
+            Ok(())
+        };
+        let rustdoc_vars_handler = |vars: &mut Element| {
+            if let Some(theme) = requested_theme.as_deref() {
+                vars.set_attribute("data-theme", theme)?;
+            }
+            Ok(())
+        };
+
+        let stylesheet_selector = "link[rel='stylesheet']".parse().unwrap();
+        let rustdoc_vars_selector = "#rustdoc-vars".parse().unwrap();
+        let element_content_handlers = vec![
+            (
+                &stylesheet_selector,
+                ElementContentHandlers::default().element(theme_stylesheet_handler),
+            ),
+            (
+                &rustdoc_vars_selector,
+                ElementContentHandlers::default().element(rustdoc_vars_handler),
+            ),
+        ];
+        let settings = Settings {
+            element_content_handlers,
+            ..Settings::default()
+        };
+
+        let mut output = Vec::new();
+        let mut writer =
+            HtmlRewriter::try_new(settings, |bytes: &[u8]| output.extend_from_slice(bytes))
+                .unwrap();
+        writer.write(html.as_bytes()).unwrap();
+        writer.end().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn theme_stylesheet_handler_enables_requested_and_disables_other_themes() {
+        let html = "<link rel=\"stylesheet\" href=\"light.css\">\n\
+                     <link rel=\"stylesheet\" href=\"dark.css\" disabled>\n\
+                     <link rel=\"stylesheet\" href=\"ayu.css\">\n";
+        let output = rewrite_theme_links_for_test(html, Some("dark"));
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].contains("disabled"), "light.css: {}", lines[0]);
+        assert!(!lines[1].contains("disabled"), "dark.css: {}", lines[1]);
+        assert!(lines[2].contains("disabled"), "ayu.css: {}", lines[2]);
+    }
+
+    #[test]
+    fn theme_stylesheet_handler_ignores_stylesheets_that_merely_contain_a_theme_name() {
+        let html = "<link rel=\"stylesheet\" href=\"highlight.css\">\n\
+                     <link rel=\"stylesheet\" href=\"syntax-light.css\">\n";
+        assert_eq!(rewrite_theme_links_for_test(html, Some("dark")), html);
+    }
+
+    #[test]
+    fn theme_handlers_are_noop_without_a_requested_theme() {
+        let html = "<link rel=\"stylesheet\" href=\"light.css\">\n\
+                     <div id=\"rustdoc-vars\"></div>";
+        assert_eq!(rewrite_theme_links_for_test(html, None), html);
+    }
+
+    #[test]
+    fn rustdoc_vars_handler_syncs_data_theme_attribute() {
+        let html = "<div id=\"rustdoc-vars\"></div>";
+        let output = rewrite_theme_links_for_test(html, Some("ayu"));
+        assert!(output.contains("data-theme=\"ayu\""));
+    }
 }
@@ -0,0 +1,144 @@
+use crate::utils::html::{rewrite_lol_to_writer, HtmlRewriteError};
+use crate::web::page::TemplateData;
+use std::io::Write;
+use std::path::Path;
+use tera::Context;
+
+/// Returns whether `path` looks like a standalone Markdown document (a
+/// guide, book chapter, or README) rather than rustdoc-generated output.
+pub(crate) fn is_markdown_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("md") | Some("markdown")
+    )
+}
+
+/// A standalone Markdown document split into its title, taken from a leading
+/// pandoc-style `% Title` line or else a `# ` heading on the first line, and
+/// its Markdown body. A `% Title` line is removed from the body; a `# `
+/// heading stays in it. A heading further down is not used as the title.
+struct MarkdownDocument<'a> {
+    title: Option<&'a str>,
+    body: &'a str,
+}
+
+/// Split rustdoc-style leading `% Title` metadata off of a Markdown
+/// document, mirroring how rustdoc itself extracts a title when it renders
+/// a standalone `.md` file.
+fn parse_front_matter(markdown: &str) -> MarkdownDocument<'_> {
+    if let Some(rest) = markdown.strip_prefix("% ") {
+        let (title, body) = rest.split_once('\n').unwrap_or((rest, ""));
+        return MarkdownDocument {
+            title: Some(title.trim()),
+            body,
+        };
+    }
+
+    if let Some(rest) = markdown.strip_prefix("# ") {
+        let title = rest.split('\n').next().unwrap_or(rest).trim();
+        return MarkdownDocument {
+            title: Some(title),
+            body: markdown,
+        };
+    }
+
+    MarkdownDocument {
+        title: None,
+        body: markdown,
+    }
+}
+
+/// Escape the handful of characters that matter inside an HTML text node or
+/// double-quoted attribute; `title` only ever comes from Markdown source
+/// text, never from already-escaped HTML.
+fn escape_html(raw: &str) -> String {
+    raw.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Render a standalone Markdown document (a guide, book chapter, or README
+/// shipped inside a crate) with the docs.rs chrome, streaming the result
+/// into `writer`.
+///
+/// rustdoc can render a plain `.md`/`.markdown` file to HTML on its own, but
+/// that output is bare: no navbar, no vendored styles, no docs.rs body
+/// wrapper. This renders the Markdown to HTML, wraps it in a minimal
+/// `<head>`/`<body>` skeleton carrying the extracted title and a stylesheet
+/// placeholder, and pipes the result through [`rewrite_lol_to_writer`] so it
+/// picks up exactly the same chrome that rustdoc pages get.
+pub(crate) fn render_markdown_page(
+    markdown: &[u8],
+    max_allowed_memory_usage: usize,
+    ctx: Context,
+    templates: &TemplateData,
+    writer: &mut dyn Write,
+) -> Result<(), HtmlRewriteError> {
+    let markdown = String::from_utf8_lossy(markdown);
+    let MarkdownDocument { title, body } = parse_front_matter(&markdown);
+
+    let mut unsafe_body_html = String::new();
+    pulldown_cmark::html::push_html(&mut unsafe_body_html, pulldown_cmark::Parser::new(body));
+
+    // `body` is untrusted: it comes straight from a README or guide shipped
+    // inside an arbitrary published crate, and pulldown-cmark passes raw
+    // inline/block HTML straight through by default. Sanitize before this
+    // ever reaches a writer, or a crate could ship a `<script>` or an
+    // `onerror=` attribute that runs on the docs.rs origin.
+    let body_html = ammonia::clean(&unsafe_body_html);
+
+    // `rewrite_lol_to_writer`'s `first_stylesheet_handler` anchors on the
+    // first `link[type='text/css'][href*='rustdoc']` to inject
+    // `vendored.css` before it; a rendered Markdown page has no rustdoc
+    // stylesheet of its own, so this placeholder gives it something to
+    // anchor on and get the same vendored styles as rustdoc pages.
+    let page = format!(
+        "<!DOCTYPE html><html><head><title>{title}</title>\
+         <link rel=\"stylesheet\" type=\"text/css\" href=\"rustdoc.css\"></head>\
+         <body>{body_html}</body></html>",
+        title = escape_html(title.unwrap_or("")),
+    );
+
+    rewrite_lol_to_writer(
+        page.as_bytes(),
+        max_allowed_memory_usage,
+        ctx,
+        templates,
+        writer,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_front_matter_reads_pandoc_style_title() {
+        let doc = parse_front_matter("% My Guide\n\nSome body text.");
+        assert_eq!(doc.title, Some("My Guide"));
+        assert_eq!(doc.body, "\nSome body text.");
+    }
+
+    #[test]
+    fn parse_front_matter_reads_atx_heading_title() {
+        let doc = parse_front_matter("# My Guide\n\nSome body text.");
+        assert_eq!(doc.title, Some("My Guide"));
+        // The heading stays part of the body so it still renders in the page.
+        assert_eq!(doc.body, "# My Guide\n\nSome body text.");
+    }
+
+    #[test]
+    fn parse_front_matter_handles_missing_title() {
+        let doc = parse_front_matter("Just a paragraph, no heading.");
+        assert_eq!(doc.title, None);
+        assert_eq!(doc.body, "Just a paragraph, no heading.");
+    }
+
+    #[test]
+    fn parse_front_matter_handles_pandoc_title_with_no_body() {
+        let doc = parse_front_matter("% Only A Title");
+        assert_eq!(doc.title, Some("Only A Title"));
+        assert_eq!(doc.body, "");
+    }
+}